@@ -0,0 +1,260 @@
+//! Role-based access control for HTTP routes.
+//!
+//! Borrows the common "permission annotation" pattern from similar Rust
+//! services: a route declares what it requires by taking a `Guard<P>`
+//! extractor, and the request is rejected with `403` before the handler
+//! runs if the caller's `Principal` doesn't hold that permission.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+};
+use libsql::params;
+use serde::{Deserialize, Serialize};
+
+use crate::pool::DbPool;
+
+/// The roles a caller can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Editor,
+    Reader,
+}
+
+impl Role {
+    fn permissions(self) -> &'static [&'static str] {
+        match self {
+            Role::Admin => &["posts:read", "posts:write"],
+            Role::Editor => &["posts:read", "posts:write"],
+            Role::Reader => &["posts:read"],
+        }
+    }
+
+    fn has_permission(self, permission: &str) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+/// The authenticated caller, resolved by looking the bearer token up in the
+/// `permissions` table (`token TEXT PRIMARY KEY, subject TEXT, role TEXT`).
+/// An unrecognized token is simply not a valid caller — there is no
+/// self-asserted fallback.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub subject: String,
+    pub role: Role,
+}
+
+impl Principal {
+    fn has_permission(&self, permission: &str) -> bool {
+        self.role.has_permission(permission)
+    }
+}
+
+/// Rejection for a missing, malformed, or unrecognized bearer token.
+pub struct AuthError(StatusCode);
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        self.0.into_response()
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for Principal
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(AuthError(StatusCode::UNAUTHORIZED))?;
+
+        let token = header
+            .strip_prefix("Bearer ")
+            .ok_or(AuthError(StatusCode::UNAUTHORIZED))?;
+
+        let pool = parts
+            .extensions
+            .get::<Arc<DbPool>>()
+            .ok_or(AuthError(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        lookup_principal(pool, token)
+            .await
+            .map_err(|_| AuthError(StatusCode::SERVICE_UNAVAILABLE))?
+            .ok_or(AuthError(StatusCode::UNAUTHORIZED))
+    }
+}
+
+/// Resolves `token` against the `permissions` table. Returns `Ok(None)` for
+/// an unrecognized token (distinct from a connection/query failure, which
+/// is surfaced as an error so it maps to `503` rather than `401`).
+async fn lookup_principal(pool: &DbPool, token: &str) -> anyhow::Result<Option<Principal>> {
+    let connection = pool.get().await?;
+    let mut results = connection
+        .query(
+            "SELECT subject, role FROM permissions WHERE token = ?",
+            params![token],
+        )
+        .await?;
+
+    let Some(row) = results.next().await? else {
+        return Ok(None);
+    };
+
+    let subject: String = row.get(0)?;
+    let role: String = row.get(1)?;
+    let role = match role.as_str() {
+        "admin" => Role::Admin,
+        "editor" => Role::Editor,
+        "reader" => Role::Reader,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Principal { subject, role }))
+}
+
+/// Seeds a single admin row into `permissions` from `BOOTSTRAP_ADMIN_TOKEN` /
+/// `BOOTSTRAP_ADMIN_SUBJECT`, if set and not already present. A freshly
+/// migrated `permissions` table starts empty, which would otherwise leave
+/// every `posts:write` route permanently unreachable — there'd be no way to
+/// mint the first valid token to then manage the rest. Operators rotate
+/// `BOOTSTRAP_ADMIN_TOKEN` out and provision further callers by inserting
+/// into `permissions` directly (or through an admin tool built on top of it)
+/// once the first admin exists.
+pub async fn seed_bootstrap_admin(pool: &DbPool) -> anyhow::Result<()> {
+    let Ok(token) = std::env::var("BOOTSTRAP_ADMIN_TOKEN") else {
+        return Ok(());
+    };
+    let subject =
+        std::env::var("BOOTSTRAP_ADMIN_SUBJECT").unwrap_or_else(|_| "bootstrap-admin".to_string());
+
+    let connection = pool.get().await?;
+    connection
+        .execute(
+            "INSERT INTO permissions (token, subject, role) VALUES (?, ?, 'admin') \
+             ON CONFLICT(token) DO NOTHING",
+            params![token, subject],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// A permission string a [`Guard`] requires, named so routes can say what
+/// they need instead of repeating a literal string at every call site.
+pub trait RequiredPermission {
+    const PERMISSION: &'static str;
+}
+
+/// Requires `posts:write` (create/update/delete).
+pub struct PostsWrite;
+
+impl RequiredPermission for PostsWrite {
+    const PERMISSION: &'static str = "posts:write";
+}
+
+/// Rejects the request with `403 Forbidden` before the handler runs unless
+/// the caller's [`Principal`] holds `P::PERMISSION`. Add `Guard<P>` as a
+/// handler argument to gate a route.
+pub struct Guard<P>(PhantomData<P>);
+
+#[axum::async_trait]
+impl<S, P> FromRequestParts<S> for Guard<P>
+where
+    S: Send + Sync,
+    P: RequiredPermission,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let principal = Principal::from_request_parts(parts, state).await?;
+        if principal.has_permission(P::PERMISSION) {
+            Ok(Guard(PhantomData))
+        } else {
+            Err(AuthError(StatusCode::FORBIDDEN))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::PoolConfig;
+
+    #[test]
+    fn reader_lacks_write_permission() {
+        let principal = Principal {
+            subject: "reader".to_string(),
+            role: Role::Reader,
+        };
+
+        assert!(!principal.has_permission(PostsWrite::PERMISSION));
+    }
+
+    #[test]
+    fn editor_and_admin_have_write_permission() {
+        for role in [Role::Editor, Role::Admin] {
+            let principal = Principal {
+                subject: "writer".to_string(),
+                role,
+            };
+            assert!(principal.has_permission(PostsWrite::PERMISSION));
+        }
+    }
+
+    async fn pool_with_permission(token: &str, role: &str) -> Arc<DbPool> {
+        let database = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let connection = database.connect().unwrap();
+        crate::migrations::migrate(&connection).await.unwrap();
+        connection
+            .execute(
+                "INSERT INTO permissions (token, subject, role) VALUES (?, 'test-subject', ?)",
+                params![token, role],
+            )
+            .await
+            .unwrap();
+
+        Arc::new(DbPool::new(database, PoolConfig::default()))
+    }
+
+    #[tokio::test]
+    async fn guard_rejects_a_caller_without_the_required_permission() {
+        let pool = pool_with_permission("reader-token", "reader").await;
+        let request = axum::http::Request::builder()
+            .header(AUTHORIZATION, "Bearer reader-token")
+            .body(())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+        parts.extensions.insert(pool);
+
+        let result = Guard::<PostsWrite>::from_request_parts(&mut parts, &()).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn guard_admits_a_caller_with_the_required_permission() {
+        let pool = pool_with_permission("admin-token", "admin").await;
+        let request = axum::http::Request::builder()
+            .header(AUTHORIZATION, "Bearer admin-token")
+            .body(())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+        parts.extensions.insert(pool);
+
+        let result = Guard::<PostsWrite>::from_request_parts(&mut parts, &()).await;
+
+        assert!(result.is_ok());
+    }
+}
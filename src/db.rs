@@ -1,15 +1,44 @@
 //! Provides SQLX integration for the test database.
 //!
-//! The database is assumed to be in-memory, and rebuilt from
-//! scratch on each start-up.
+//! The schema is brought up to date by `migrations::migrate` on each
+//! start-up; see `DbMode` for the supported ways of connecting to it
+//! (remote Turso, local SQLite file, or an embedded replica of Turso).
 
-use anyhow::{Error, Ok, Result};
-use axum::http::StatusCode;
-use libsql::{params, Connection, Error as LibsqlError};
+use libsql::params;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 // use sqlx::{FromRow, Row, SqlitePool};
-use tokio::sync::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::error::{AppError, Result};
+use crate::outbox;
+use crate::pool::{DbPool, PoolConfig};
+
+/// Which way `init_db()` opens the database.
+///
+/// Read from the `DB_MODE` environment variable: `remote` (default) talks
+/// to Turso over the network for every query; `local` opens a plain
+/// on-disk SQLite file; `embedded-replica` keeps a local replica that is
+/// synced from Turso, so reads are fast and the service degrades
+/// gracefully if the network is down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DbMode {
+    Remote,
+    Local,
+    EmbeddedReplica,
+}
+
+impl DbMode {
+    fn from_env() -> Self {
+        match std::env::var("DB_MODE").unwrap_or_default().to_lowercase().as_str() {
+            "local" => DbMode::Local,
+            "embedded-replica" | "embedded_replica" => DbMode::EmbeddedReplica,
+            _ => DbMode::Remote,
+        }
+    }
+}
 
 /// Represents a book, taken from the books table in SQLite.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,30 +56,80 @@ pub struct Post {
     pub created_at: Option<String>,
 }
 
+/// A keyed, optionally time-limited cache of posts. Caching by ID means a
+/// single-row update only needs to drop that one entry instead of the
+/// whole cache, and `book_by_id` benefits from caching too.
 struct PostCache {
-    all_books: RwLock<Option<Vec<Post>>>,
+    entries: RwLock<HashMap<i32, (Post, Instant)>>,
+    all_loaded: RwLock<bool>,
+    ttl: Option<Duration>,
 }
 
 impl PostCache {
     fn new() -> Self {
         Self {
-            all_books: RwLock::new(None),
+            entries: RwLock::new(HashMap::new()),
+            all_loaded: RwLock::new(false),
+            ttl: std::env::var("POST_CACHE_TTL_SECS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs),
+        }
+    }
+
+    fn is_expired(&self, inserted_at: Instant) -> bool {
+        self.ttl.is_some_and(|ttl| inserted_at.elapsed() > ttl)
+    }
+
+    async fn get(&self, id: i32) -> Option<Post> {
+        let entries = self.entries.read().await;
+        let (post, inserted_at) = entries.get(&id)?;
+        (!self.is_expired(*inserted_at)).then(|| post.clone())
+    }
+
+    async fn all(&self) -> Option<Vec<Post>> {
+        if !*self.all_loaded.read().await {
+            return None;
+        }
+
+        let entries = self.entries.read().await;
+        if entries.values().any(|(_, inserted_at)| self.is_expired(*inserted_at)) {
+            return None;
         }
+
+        let mut posts: Vec<Post> = entries.values().map(|(post, _)| post.clone()).collect();
+        posts.sort_by(|a, b| a.title.cmp(&b.title).then(a.author_id.cmp(&b.author_id)));
+        Some(posts)
     }
 
-    async fn all_books(&self) -> Option<Vec<Post>> {
-        let lock = self.all_books.read().await;
-        lock.clone()
+    async fn refresh_all(&self, posts: Vec<Post>) {
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        entries.clear();
+        for post in posts {
+            if let Some(id) = post.id {
+                entries.insert(id, (post, now));
+            }
+        }
+        drop(entries);
+        *self.all_loaded.write().await = true;
+    }
+
+    async fn put(&self, post: Post) {
+        if let Some(id) = post.id {
+            self.entries.write().await.insert(id, (post, Instant::now()));
+        }
     }
 
-    async fn refresh(&self, books: Vec<Post>) {
-        let mut lock = self.all_books.write().await;
-        *lock = Some(books);
+    /// Drops a single entry and marks the `all_books` snapshot stale,
+    /// without touching any other cached post.
+    async fn invalidate_entry(&self, id: i32) {
+        self.entries.write().await.remove(&id);
+        *self.all_loaded.write().await = false;
     }
 
-    async fn invalidate(&self) {
-        let mut lock = self.all_books.write().await;
-        *lock = None;
+    async fn invalidate_list(&self) {
+        *self.all_loaded.write().await = false;
     }
 }
 
@@ -60,209 +139,309 @@ static CACHE: Lazy<PostCache> = Lazy::new(PostCache::new);
 ///
 /// ## Returns
 /// * A ready-to-use connection pool.
-pub async fn init_db() -> Result<Connection> {
-    let url = std::env::var("TURSO_DATABASE_URL").expect("TURSO_DATABASE_URL must be set");
-    let token = std::env::var("TURSO_AUTH_TOKEN").unwrap_or_default();
+pub async fn init_db() -> anyhow::Result<DbPool> {
+    let db = match DbMode::from_env() {
+        DbMode::Remote => {
+            let url = std::env::var("TURSO_DATABASE_URL").expect("TURSO_DATABASE_URL must be set");
+            let token = std::env::var("TURSO_AUTH_TOKEN").unwrap_or_default();
 
-    let db = libsql::Builder::new_remote(url, token).build().await?;
-    let connection = db.connect().unwrap();
+            libsql::Builder::new_remote(url, token).build().await?
+        }
+        DbMode::Local => {
+            let path = std::env::var("DB_PATH").unwrap_or_else(|_| "local.db".to_string());
+
+            libsql::Builder::new_local(path).build().await?
+        }
+        DbMode::EmbeddedReplica => {
+            let path = std::env::var("DB_PATH").unwrap_or_else(|_| "replica.db".to_string());
+            let url = std::env::var("TURSO_DATABASE_URL").expect("TURSO_DATABASE_URL must be set");
+            let token = std::env::var("TURSO_AUTH_TOKEN").unwrap_or_default();
+
+            let db = libsql::Builder::new_remote_replica(path, url, token)
+                .build()
+                .await?;
+            db.sync().await?;
+            spawn_replica_sync(db.clone());
+            db
+        }
+    };
+
+    crate::migrations::migrate(&db.connect()?).await?;
+
+    let pool = DbPool::new(db, PoolConfig::from_env());
+    crate::auth::seed_bootstrap_admin(&pool).await?;
+
+    Ok(pool)
+}
+
+/// Spawns a background task that periodically calls `sync()` on an
+/// embedded-replica database, so local reads stay close to what's on the
+/// remote. The interval is configurable via `DB_SYNC_INTERVAL_SECS`
+/// (default 30s).
+fn spawn_replica_sync(db: libsql::Database) {
+    // `tokio::time::interval` panics on a zero period, so a zero value is
+    // raised to 1s and an unparseable one falls back to the 30s default.
+    let interval_secs = std::env::var("DB_SYNC_INTERVAL_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30)
+        .max(1);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            ticker.tick().await;
+            if let Err(err) = db.sync().await {
+                eprintln!("embedded-replica sync failed: {err}");
+            }
+        }
+    });
+}
 
-    Ok(connection)
+/// Decodes a row into a `Post`. A column that doesn't decode as expected is
+/// a schema/data bug, not a connectivity problem, so it's reported as
+/// [`AppError::Deserialization`] rather than going through the blanket
+/// `libsql::Error` conversion (which maps to `503`).
+fn post_from_row(row: &libsql::Row) -> Result<Post> {
+    (|| -> std::result::Result<Post, libsql::Error> {
+        Ok(Post {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            content: row.get(2)?,
+            author_id: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    })()
+    .map_err(|err| AppError::Deserialization(err.into()))
 }
 
 /// Retrieves all books, sorted by title and then author.
 ///
 /// ## Arguments
-/// * `connection_pool` - the connection pool to use.
+/// * `pool` - the connection pool to use.
 ///
 /// ## Returns
 /// * A vector of books, or an error.
-pub async fn all_books(connection: Connection) -> Result<Vec<Post>> {
-    if let Some(all_books) = CACHE.all_books().await {
-        Ok(all_books)
-    } else {
-        // let books = sqlx::query_as::<_, Book>("SELECT * FROM books ORDER BY title,author")
-        //     .fetch_all(connection)
-        //     .await?;
-
-        let mut results = connection.query("SELECT * FROM users", ()).await.unwrap();
-
-        let mut books: Vec<Post> = Vec::new();
-
-        while let Some(row) = results.next().await.unwrap() {
-            let item: Post = Post {
-                id: row.get(0).unwrap(),
-                title: row.get(1).unwrap(),
-                content: row.get(2).unwrap(),
-                author_id: row.get(3).unwrap(),
-                created_at: row.get(4).unwrap(),
-            };
-            books.push(item);
-        }
+pub async fn all_books(pool: &DbPool) -> Result<Vec<Post>> {
+    if let Some(all_books) = CACHE.all().await {
+        return Ok(all_books);
+    }
+
+    // let books = sqlx::query_as::<_, Book>("SELECT * FROM books ORDER BY title,author")
+    //     .fetch_all(connection)
+    //     .await?;
+
+    let connection = pool.get().await?;
+    let mut results = connection.query("SELECT * FROM posts ORDER BY title, author_id", ()).await?;
 
-        CACHE.refresh(books.clone()).await;
-        Ok(books)
+    let mut books: Vec<Post> = Vec::new();
+    while let Some(row) = results.next().await? {
+        books.push(post_from_row(&row)?);
     }
+
+    CACHE.refresh_all(books.clone()).await;
+    Ok(books)
 }
 
 /// Retrieves a single book, by ID
 ///
 /// ## Arguments
-/// * `connection_pool` - the database connection pool to use
+/// * `pool` - the database connection pool to use
 /// * `id` - the primary key of the book to retrieve
-pub async fn book_by_id(connection: Connection, id: i32) -> Result<Post> {
+pub async fn book_by_id(pool: &DbPool, id: i32) -> Result<Post> {
     // Ok(sqlx::query_as::<_, Post>("SELECT * FROM books WHERE id=$1")
     //     .bind(id)
     //     .fetch_one(connection_pool)
     //     .await?)
+    if let Some(post) = CACHE.get(id).await {
+        return Ok(post);
+    }
+
+    let connection = pool.get().await?;
     let mut results = connection
-        .query("SELECT * FROM users WHERE id == ?", params![id])
-        .await
-        .map_err(|_err: LibsqlError| return LibsqlError::NullValue)?;
+        .query("SELECT * FROM posts WHERE id == ?", params![id])
+        .await?;
 
-    // let row = results.next().await.unwrap().unwrap();
-    let mut posts: Vec<Post> = vec![];
+    let post = match results.next().await? {
+        Some(row) => post_from_row(&row)?,
+        None => return Err(AppError::NotFound(format!("post {id} not found"))),
+    };
 
-    while let Some(row) = results.next().await? {
-        let row = Post {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            content: row.get(2)?,
-            author_id: row.get(3)?,
-            created_at: row.get(4)?,
-        };
-        posts.push(row)
+    CACHE.put(post.clone()).await;
+    Ok(post)
+}
+
+/// Adds a post to the database.
+///
+/// The insert and its `post.created` outbox row are written in the same
+/// transaction, so a crash between them can't leave one without the other.
+///
+/// ## Arguments
+/// * `pool` - the database connection pool to use
+/// * `post` - the post to insert. The `id` field is ignored; the primary
+///            key is assigned by SQLite.
+///
+/// ## Returns
+/// * The primary key value of the new post
+pub async fn add_post(pool: &DbPool, post: &Post) -> Result<i32> {
+    let connection = pool.get().await?;
+    let tx = connection.transaction().await?;
+
+    let mut results = tx
+        .query(
+            "INSERT INTO posts (title, content, author_id, created_at) VALUES (?,?,?,?) RETURNING id",
+            params![
+                post.title.clone(),
+                post.content.clone(),
+                post.author_id.clone(),
+                post.created_at.clone()
+            ],
+        )
+        .await?;
+
+    let row = results
+        .next()
+        .await?
+        .ok_or_else(|| AppError::Internal(anyhow::Error::msg("INSERT did not return an id")))?;
+    let id: i32 = row.get(0)?;
+
+    let mut inserted = post.clone();
+    inserted.id = Some(id);
+    outbox::enqueue(&tx, "post.created", &inserted).await?;
+
+    tx.commit().await?;
+
+    CACHE.invalidate_list().await;
+    Ok(id)
+}
+
+/// Update a post
+///
+/// The update and its `post.updated` outbox row are written in the same
+/// transaction.
+///
+/// ## Arguments
+/// * `pool` - the database connection pool to use
+/// * `post` - the post object to update. The primary key will be used to
+///            determine which row is updated.
+pub async fn update_post(pool: &DbPool, post: &Post) -> Result<()> {
+    let id = post
+        .id
+        .ok_or_else(|| AppError::Internal(anyhow::Error::msg("post id is required for update")))?;
+
+    let connection = pool.get().await?;
+    let tx = connection.transaction().await?;
+
+    let rows_affected = tx
+        .execute(
+            "UPDATE posts SET title=?, content=?, author_id=? WHERE id=?",
+            params![
+                post.title.clone(),
+                post.content.clone(),
+                post.author_id.clone(),
+                id
+            ],
+        )
+        .await?;
+
+    if rows_affected == 0 {
+        return Err(AppError::NotFound(format!("post {id} not found")));
     }
 
-    match posts.get(0) {
-        Some(post) => Ok(post.clone()),
-        None => Err(Error::msg("ID NOT FOUND".to_string())),
+    outbox::enqueue(&tx, "post.updated", post).await?;
+    tx.commit().await?;
+
+    CACHE.invalidate_entry(id).await;
+    Ok(())
+}
+
+/// Delete a post
+///
+/// The delete and its `post.deleted` outbox row are written in the same
+/// transaction.
+///
+/// ## Arguments
+/// * `pool` - the database connection pool to use
+/// * `id` - the primary key of the post to delete
+pub async fn delete_post(pool: &DbPool, id: i32) -> Result<()> {
+    let connection = pool.get().await?;
+    let tx = connection.transaction().await?;
+
+    let rows_affected = tx
+        .execute("DELETE FROM posts WHERE id=?", params![id])
+        .await?;
+
+    if rows_affected == 0 {
+        return Err(AppError::NotFound(format!("post {id} not found")));
     }
 
-    // Ok(post)
+    let deleted = Post {
+        id: Some(id),
+        title: String::new(),
+        content: String::new(),
+        author_id: String::new(),
+        created_at: None,
+    };
+    outbox::enqueue(&tx, "post.deleted", &deleted).await?;
+    tx.commit().await?;
+
+    CACHE.invalidate_entry(id).await;
+    Ok(())
 }
 
-// /// Adds a book to the database.
-// ///
-// /// ## Arguments
-// /// * `connection_pool` - the database connection to use
-// /// * `title` - the title of the book to add
-// /// * `author` - the author of the book to add
-// ///
-// /// ## Returns
-// /// * The primary key value of the new book
-// pub async fn add_book<S: ToString>(connection: Connection, title: S, author_id: S) -> Result<i32> {
-//     let title = title.to_string();
-//     let author_id = author_id.to_string();
-
-//     let post = Post {
-//         id: None,
-//         title,
-//         content: String::from("foo"),
-//         author_id,
-//         created_at: None,
-//     };
-
-//     // let id = sqlx::query("INSERT INTO books (title, author) VALUES ($1, $2) RETURNING id")
-//     //     .bind(title)
-//     //     .bind(author)
-//     //     .fetch_one(connection_pool)
-//     //     .await?
-//     //     .get(0);
-
-//     let results = connection
-//         .query(
-//             "INSERT into posts values (?1, ?2, ?3)",
-//             params![post.title.clone(), post.content.clone()],
-//         )
-//         .await;
-//     CACHE.invalidate().await;
-//     Ok(id)
-// }
-
-// /// Update a book
-// ///
-// /// ## Arguments
-// /// * `connection_pool` - the database connection to use
-// /// * `book` - the book object to update. The primary key will be used to
-// ///            determine which row is updated.
-// pub async fn update_book(connection: Connection, post: &Post) -> Result<()> {
-//     // sqlx::query("UPDATE books SET title=$1, author=$2 WHERE id=$3")
-//     //     .bind(&book.title)
-//     //     .bind(&book.author_id)
-//     //     .bind(&book.id)
-//     //     .execute(connection_pool)
-//     //     .await?;
-//     CACHE.invalidate().await;
-//     Ok(())
-// }
-
-// /// Delete a book
-// ///
-// /// ## Arguments
-// /// * `connection_pool` - the database connection to use
-// /// * `id` - the primary key of the book to delete
-// pub async fn delete_book(connection_pool: Connection, id: i32) -> Result<()> {
-//     sqlx::query("DELETE FROM books WHERE id=$1")
-//         .bind(id)
-//         .execute(connection_pool)
-//         .await?;
-//     CACHE.invalidate().await;
-//     Ok(())
-// }
-
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-
-//     #[sqlx::test]
-//     async fn get_all() {
-//         dotenv::dotenv().ok();
-//         let cnn = init_db().await.unwrap();
-//         let all_rows = all_books(&cnn).await.unwrap();
-//         assert!(!all_rows.is_empty());
-//     }
-
-//     #[sqlx::test]
-//     async fn get_one() {
-//         dotenv::dotenv().ok();
-//         let cnn = init_db().await.unwrap();
-//         let book = book_by_id(&cnn, 1).await.unwrap();
-//         assert_eq!(1, book.id);
-//         assert_eq!("Hands-on Rust", book.title);
-//         assert_eq!("Wolverson, Herbert", book.author);
-//     }
-
-//     #[sqlx::test]
-//     async fn test_create() {
-//         dotenv::dotenv().ok();
-//         let cnn = init_db().await.unwrap();
-//         let new_id = add_book(&cnn, "Test Book", "Test Author").await.unwrap();
-//         let new_book = book_by_id(&cnn, new_id).await.unwrap();
-//         assert_eq!(new_id, new_book.id);
-//         assert_eq!("Test Book", new_book.title);
-//         assert_eq!("Test Author", new_book.author);
-//     }
-
-//     #[sqlx::test]
-//     async fn test_update() {
-//         dotenv::dotenv().ok();
-//         let cnn = init_db().await.unwrap();
-//         let mut book = book_by_id(&cnn, 2).await.unwrap();
-//         book.title = "Updated Book".to_string();
-//         update_book(&cnn, &book).await.unwrap();
-//         let updated_book = book_by_id(&cnn, 2).await.unwrap();
-//         assert_eq!("Updated Book", updated_book.title);
-//     }
-
-//     #[sqlx::test]
-//     async fn test_delete() {
-//         dotenv::dotenv().ok();
-//         let cnn = init_db().await.unwrap();
-//         let new_id = add_book(&cnn, "DeleteMe", "Test Author").await.unwrap();
-//         let _new_book = book_by_id(&cnn, new_id).await.unwrap();
-//         delete_book(&cnn, new_id).await.unwrap();
-//         let all_books = all_books(&cnn).await.unwrap();
-//         assert!(all_books.iter().find(|b| b.title == "DeleteMe").is_none());
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(id: i32, title: &str) -> Post {
+        Post {
+            id: Some(id),
+            title: title.to_string(),
+            content: "content".to_string(),
+            author_id: "author".to_string(),
+            created_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_and_all_are_populated_by_refresh_all() {
+        let cache = PostCache::new();
+        cache.refresh_all(vec![post(1, "a"), post(2, "b")]).await;
+
+        assert_eq!(cache.get(1).await.unwrap().title, "a");
+        assert_eq!(cache.all().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn invalidate_entry_drops_only_that_row() {
+        let cache = PostCache::new();
+        cache.refresh_all(vec![post(1, "a"), post(2, "b")]).await;
+
+        cache.invalidate_entry(1).await;
+
+        assert!(cache.get(1).await.is_none());
+        assert!(cache.get(2).await.is_some());
+        // invalidating one row also marks the full-list snapshot stale.
+        assert!(cache.all().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn invalidate_list_leaves_individual_entries_cached() {
+        let cache = PostCache::new();
+        cache.refresh_all(vec![post(1, "a")]).await;
+
+        cache.invalidate_list().await;
+
+        assert!(cache.get(1).await.is_some());
+        assert!(cache.all().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn put_overwrites_an_existing_entry() {
+        let cache = PostCache::new();
+        cache.put(post(1, "a")).await;
+        cache.put(post(1, "b")).await;
+
+        assert_eq!(cache.get(1).await.unwrap().title, "b");
+    }
+}
@@ -0,0 +1,63 @@
+//! Maps db-layer failures onto HTTP responses instead of panicking on them.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+/// An error from the db layer, with enough shape to pick the right status
+/// code instead of the caller having to inspect an opaque `anyhow::Error`.
+#[derive(Debug)]
+pub enum AppError {
+    /// The requested row doesn't exist. Maps to `404`.
+    NotFound(String),
+    /// The database is unreachable or the connection pool couldn't hand
+    /// out a connection in time. Maps to `503`.
+    Unavailable(anyhow::Error),
+    /// A row couldn't be turned back into a `Post`. Maps to `500`.
+    Deserialization(anyhow::Error),
+    /// Anything else. Maps to `500`.
+    Internal(anyhow::Error),
+}
+
+/// Convenience alias so db-layer functions can just write `Result<Post>`.
+pub type Result<T> = std::result::Result<T, AppError>;
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::NotFound(message) => write!(f, "{message}"),
+            AppError::Unavailable(err) => write!(f, "{err}"),
+            AppError::Deserialization(err) => write!(f, "{err}"),
+            AppError::Internal(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Deserialization(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        (status, self.to_string()).into_response()
+    }
+}
+
+impl From<libsql::Error> for AppError {
+    fn from(err: libsql::Error) -> Self {
+        AppError::Unavailable(err.into())
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        AppError::Unavailable(err)
+    }
+}
@@ -1,17 +1,24 @@
 #![allow(unused)]
 
+mod auth;
 mod db;
+mod error;
+mod migrations;
+mod outbox;
+mod pool;
 mod rest;
 
+use std::sync::Arc;
+
 use crate::db::init_db;
+use crate::pool::DbPool;
 use anyhow::Result;
 use axum::{Extension, Router};
-use libsql::Connection;
 // use sqlx::SqlitePool;
 
 /// Build the overall web service router.
 /// Constructing the router in a function makes it easy to re-use in unit tests.
-fn router(connection_pool: Connection) -> Router {
+fn router(connection_pool: Arc<DbPool>) -> Router {
     Router::new()
         // Nest service allows you to attach another router to a URL base.
         // "/" inside the service will be "/books" to the outside world.
@@ -26,10 +33,13 @@ async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
 
     // Initialize the database and obtain a connection pool
-    let connection = init_db().await?;
+    let connection_pool = Arc::new(init_db().await?);
+
+    // Start the outbox worker that delivers post-change events
+    outbox::spawn_worker(connection_pool.clone(), outbox::OutboxConfig::from_env());
 
     // Initialize the Axum routing service
-    let app: Router = router(connection);
+    let app: Router = router(connection_pool);
 
     // Define the address to listen on (everything)
     // let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
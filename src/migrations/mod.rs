@@ -0,0 +1,121 @@
+//! Embedded schema migrations, applied in order on startup.
+//!
+//! Each migration is a plain `.sql` file embedded at compile time with
+//! `include_str!`. Applied versions are tracked in a `_migrations` table,
+//! so re-running `migrate()` against an up-to-date database is a no-op.
+//! This gives the remote Turso database and local dev the same,
+//! reproducible schema.
+
+use anyhow::Result;
+use libsql::{params, Connection};
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_posts_table",
+        sql: include_str!("0001_create_posts_table.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "create_outbox_table",
+        sql: include_str!("0002_create_outbox_table.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "add_outbox_claimed_at",
+        sql: include_str!("0003_add_outbox_claimed_at.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "create_permissions_table",
+        sql: include_str!("0004_create_permissions_table.sql"),
+    },
+];
+
+/// Runs any migrations that haven't yet been applied to `connection`, in
+/// version order, each inside its own transaction. Aborts on the first
+/// failure, leaving the schema as it was before that migration started.
+pub async fn migrate(connection: &Connection) -> Result<()> {
+    connection
+        .execute(
+            "CREATE TABLE IF NOT EXISTS _migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TEXT NOT NULL
+            )",
+            (),
+        )
+        .await?;
+
+    for migration in MIGRATIONS {
+        if is_applied(connection, migration.version).await? {
+            continue;
+        }
+
+        let tx = connection.transaction().await?;
+        tx.execute_batch(migration.sql).await?;
+        tx.execute(
+            "INSERT INTO _migrations (version, name, applied_at) VALUES (?, ?, datetime('now'))",
+            params![migration.version, migration.name],
+        )
+        .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+async fn is_applied(connection: &Connection, version: i64) -> Result<bool> {
+    let mut rows = connection
+        .query(
+            "SELECT 1 FROM _migrations WHERE version = ?",
+            params![version],
+        )
+        .await?;
+    Ok(rows.next().await?.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_sorted_and_unique() {
+        let versions: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+
+        let mut sorted = versions.clone();
+        sorted.sort();
+        assert_eq!(versions, sorted, "migrations must be declared in version order");
+
+        let mut unique = versions.clone();
+        unique.dedup();
+        assert_eq!(versions.len(), unique.len(), "migration versions must be unique");
+    }
+
+    #[tokio::test]
+    async fn migrate_applies_every_version_and_is_idempotent() {
+        let db = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let connection = db.connect().unwrap();
+
+        migrate(&connection).await.unwrap();
+        migrate(&connection).await.unwrap();
+
+        let mut rows = connection
+            .query("SELECT version FROM _migrations ORDER BY version", ())
+            .await
+            .unwrap();
+        let mut applied = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            applied.push(row.get::<i64>(0).unwrap());
+        }
+
+        let expected: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(applied, expected);
+    }
+}
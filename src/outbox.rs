@@ -0,0 +1,374 @@
+//! Transactional outbox for post-change event delivery.
+//!
+//! `enqueue` is called in the same transaction as the `posts` write it
+//! describes, so the data change and the notification either both commit
+//! or neither does. A background worker then polls for due rows and
+//! delivers them over HTTP with exponential backoff plus jitter, marking
+//! each row `done` on success or `dead` once `max_attempts` attempts have
+//! failed.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use libsql::{params, Connection, Transaction};
+use once_cell::sync::Lazy;
+use tokio::sync::RwLock;
+
+use crate::db::Post;
+use crate::pool::DbPool;
+
+/// Tunables for the outbox worker, read from the environment.
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    pub endpoints: Vec<String>,
+    pub poll_interval: Duration,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub max_attempts: u32,
+    /// Consecutive failures before an endpoint is considered down and
+    /// skipped until a delivery to it succeeds again.
+    pub down_threshold: u32,
+    /// How long a row may sit `in_flight` before a worker crash is assumed
+    /// and it's requeued as `pending` for another attempt.
+    pub claim_timeout: Duration,
+}
+
+impl OutboxConfig {
+    pub fn from_env() -> Self {
+        let endpoints = std::env::var("OUTBOX_ENDPOINTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Self {
+            endpoints,
+            poll_interval: Duration::from_secs(env_u64("OUTBOX_POLL_INTERVAL_SECS", 5)),
+            base_delay: Duration::from_secs(env_u64("OUTBOX_BASE_DELAY_SECS", 1)),
+            max_delay: Duration::from_secs(env_u64("OUTBOX_MAX_DELAY_SECS", 300)),
+            max_attempts: env_u64("OUTBOX_MAX_ATTEMPTS", 10) as u32,
+            down_threshold: env_u64("OUTBOX_DOWN_THRESHOLD", 5) as u32,
+            claim_timeout: Duration::from_secs(env_u64("OUTBOX_CLAIM_TIMEOUT_SECS", 300)),
+        }
+    }
+}
+
+fn env_u64(key: &str, default: u64) -> u64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Per-endpoint consecutive-failure counts, so a down endpoint is skipped
+/// on later polls instead of being hit on every due row.
+static ENDPOINT_FAILURES: Lazy<RwLock<HashMap<String, u32>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+async fn is_endpoint_down(endpoint: &str, threshold: u32) -> bool {
+    ENDPOINT_FAILURES
+        .read()
+        .await
+        .get(endpoint)
+        .is_some_and(|failures| *failures >= threshold)
+}
+
+async fn record_success(endpoint: &str) {
+    ENDPOINT_FAILURES.write().await.remove(endpoint);
+}
+
+async fn record_failure(endpoint: &str) {
+    let mut failures = ENDPOINT_FAILURES.write().await;
+    *failures.entry(endpoint.to_string()).or_insert(0) += 1;
+}
+
+/// Inserts a row recording `event_type` for `post`. Must be called inside
+/// the same transaction as the data change it describes.
+pub async fn enqueue(tx: &Transaction, event_type: &str, post: &Post) -> Result<()> {
+    let payload = serde_json::to_string(post)?;
+
+    tx.execute(
+        "INSERT INTO outbox (event_type, payload_json, attempts, next_attempt_at, state) VALUES (?, ?, 0, ?, 'pending')",
+        params![event_type, payload, unix_now() as i64],
+    )
+    .await?;
+
+    Ok(())
+}
+
+struct DueRow {
+    id: i64,
+    payload_json: String,
+    attempts: u32,
+}
+
+/// Spawns the background worker that polls for due rows and delivers them.
+pub fn spawn_worker(pool: Arc<DbPool>, config: OutboxConfig) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.poll_interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = deliver_due_rows(&pool, &config).await {
+                eprintln!("outbox worker: {err}");
+            }
+        }
+    });
+}
+
+async fn deliver_due_rows(pool: &DbPool, config: &OutboxConfig) -> Result<()> {
+    let connection = pool.get().await?;
+
+    requeue_stale_claims(&connection, config.claim_timeout).await?;
+
+    let mut due = Vec::new();
+    let mut results = connection
+        .query(
+            "SELECT id, payload_json, attempts FROM outbox WHERE state = 'pending' AND next_attempt_at <= ?",
+            params![unix_now() as i64],
+        )
+        .await?;
+    while let Some(row) = results.next().await? {
+        due.push(DueRow {
+            id: row.get(0)?,
+            payload_json: row.get(1)?,
+            attempts: row.get::<i64>(2)? as u32,
+        });
+    }
+
+    for row in due {
+        // Atomic claim: if another worker already flipped this row out of
+        // 'pending', `claimed` is 0 and we leave it alone.
+        let claimed = connection
+            .execute(
+                "UPDATE outbox SET state = 'in_flight', claimed_at = ? WHERE id = ? AND state = 'pending'",
+                params![unix_now() as i64, row.id],
+            )
+            .await?;
+        if claimed == 0 {
+            continue;
+        }
+
+        deliver_row(&connection, config, row).await?;
+    }
+
+    Ok(())
+}
+
+/// Resets rows that have been `in_flight` for longer than `claim_timeout`
+/// back to `pending`, so a worker that crashed (or panicked) mid-delivery
+/// doesn't strand them forever. Delivery itself stays idempotent-ish on the
+/// receiving end's responsibility; this only guarantees a retry happens.
+async fn requeue_stale_claims(connection: &Connection, claim_timeout: Duration) -> Result<()> {
+    let stale_before = unix_now().saturating_sub(claim_timeout.as_secs());
+
+    connection
+        .execute(
+            "UPDATE outbox SET state = 'pending', claimed_at = NULL \
+             WHERE state = 'in_flight' AND claimed_at <= ?",
+            params![stale_before as i64],
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn deliver_row(connection: &Connection, config: &OutboxConfig, row: DueRow) -> Result<()> {
+    if send_to_all_endpoints(config, &row.payload_json).await {
+        connection
+            .execute(
+                "UPDATE outbox SET state = 'done' WHERE id = ?",
+                params![row.id],
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let attempts = row.attempts + 1;
+    if attempts >= config.max_attempts {
+        connection
+            .execute(
+                "UPDATE outbox SET attempts = ?, state = 'dead' WHERE id = ?",
+                params![attempts, row.id],
+            )
+            .await?;
+    } else {
+        let next_attempt_at = unix_now() + backoff_delay(attempts, config).as_secs();
+        connection
+            .execute(
+                "UPDATE outbox SET attempts = ?, next_attempt_at = ?, state = 'pending', claimed_at = NULL WHERE id = ?",
+                params![attempts, next_attempt_at as i64, row.id],
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Delivers to every configured endpoint, skipping ones currently
+/// considered down. Returns `true` only if no endpoint was skipped and
+/// every endpoint that was tried accepted the event.
+async fn send_to_all_endpoints(config: &OutboxConfig, payload_json: &str) -> bool {
+    if config.endpoints.is_empty() {
+        return true;
+    }
+
+    let client = reqwest::Client::new();
+    let mut all_ok = true;
+
+    for endpoint in &config.endpoints {
+        if is_endpoint_down(endpoint, config.down_threshold).await {
+            all_ok = false;
+            continue;
+        }
+
+        let delivered = client
+            .post(endpoint)
+            .header("content-type", "application/json")
+            .body(payload_json.to_string())
+            .send()
+            .await
+            .map(|response| response.status().is_success())
+            .unwrap_or(false);
+
+        if delivered {
+            record_success(endpoint).await;
+        } else {
+            record_failure(endpoint).await;
+            all_ok = false;
+        }
+    }
+
+    all_ok
+}
+
+/// Exponential backoff capped at `max_delay`, plus under 250ms of jitter.
+/// `deliver_row` stores `next_attempt_at` in whole seconds, so the jitter is
+/// truncated away there: rows that fail in the same second with the same
+/// attempt count are due again in the same second.
+fn backoff_delay(attempts: u32, config: &OutboxConfig) -> Duration {
+    let exponential = config.base_delay.saturating_mul(2u32.saturating_pow(attempts));
+    let capped = exponential.min(config.max_delay);
+    let jitter = Duration::from_millis((unix_now_nanos() % 250) as u64);
+    capped + jitter
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn unix_now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+
+    async fn test_connection() -> Connection {
+        let db = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let connection = db.connect().unwrap();
+        migrations::migrate(&connection).await.unwrap();
+        connection
+    }
+
+    async fn insert_row(connection: &Connection, state: &str, claimed_at: Option<i64>) {
+        connection
+            .execute(
+                "INSERT INTO outbox (event_type, payload_json, attempts, next_attempt_at, state, claimed_at) VALUES ('post.created', '{}', 0, 0, ?, ?)",
+                params![state, claimed_at],
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn claiming_a_pending_row_is_exclusive() {
+        let connection = test_connection().await;
+        insert_row(&connection, "pending", None).await;
+
+        let first = connection
+            .execute(
+                "UPDATE outbox SET state = 'in_flight', claimed_at = 1 WHERE id = 1 AND state = 'pending'",
+                (),
+            )
+            .await
+            .unwrap();
+        let second = connection
+            .execute(
+                "UPDATE outbox SET state = 'in_flight', claimed_at = 1 WHERE id = 1 AND state = 'pending'",
+                (),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(first, 1, "first claim should affect the row");
+        assert_eq!(second, 0, "second claim should see the row as no longer pending");
+    }
+
+    #[tokio::test]
+    async fn stale_in_flight_rows_are_requeued() {
+        let connection = test_connection().await;
+        insert_row(&connection, "in_flight", Some(0)).await;
+
+        requeue_stale_claims(&connection, Duration::from_secs(0)).await.unwrap();
+
+        let mut rows = connection
+            .query("SELECT state, claimed_at FROM outbox WHERE id = 1", ())
+            .await
+            .unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        let state: String = row.get(0).unwrap();
+        let claimed_at: Option<i64> = row.get(1).unwrap();
+
+        assert_eq!(state, "pending");
+        assert_eq!(claimed_at, None);
+    }
+
+    #[tokio::test]
+    async fn fresh_in_flight_rows_are_left_alone() {
+        let connection = test_connection().await;
+        insert_row(&connection, "in_flight", Some(unix_now() as i64)).await;
+
+        requeue_stale_claims(&connection, Duration::from_secs(300)).await.unwrap();
+
+        let mut rows = connection
+            .query("SELECT state FROM outbox WHERE id = 1", ())
+            .await
+            .unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        let state: String = row.get(0).unwrap();
+
+        assert_eq!(state, "in_flight");
+    }
+
+    #[test]
+    fn backoff_delay_grows_with_attempts_and_respects_the_cap() {
+        let config = OutboxConfig {
+            endpoints: vec![],
+            poll_interval: Duration::from_secs(5),
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            max_attempts: 10,
+            down_threshold: 5,
+            claim_timeout: Duration::from_secs(300),
+        };
+
+        let early = backoff_delay(1, &config);
+        let late = backoff_delay(10, &config);
+
+        assert!(early >= Duration::from_secs(1));
+        assert!(late > early);
+        assert!(late <= Duration::from_secs(10) + Duration::from_millis(250));
+    }
+}
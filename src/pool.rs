@@ -0,0 +1,137 @@
+//! A small deadpool-style async connection pool for libsql.
+//!
+//! `init_db()` previously handed out a single, cloned `libsql::Connection`
+//! that was shared across every request, serializing concurrent queries.
+//! `DbPool` instead owns the `libsql::Database` handle and checks out a
+//! fresh, health-checked connection per caller, bounded by a semaphore so
+//! no more than `max_size` are in flight at once.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use libsql::{Connection, Database};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Tunables for [`DbPool`].
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    /// Maximum number of connections checked out at once.
+    pub max_size: usize,
+    /// How long `get()` will wait for a free slot before giving up.
+    pub acquire_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            acquire_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Reads `DB_POOL_MAX_SIZE` and `DB_POOL_ACQUIRE_TIMEOUT_SECS`, falling
+    /// back to [`Default`] for whichever is unset or unparseable.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            max_size: std::env::var("DB_POOL_MAX_SIZE")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default.max_size),
+            acquire_timeout: std::env::var("DB_POOL_ACQUIRE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.acquire_timeout),
+        }
+    }
+}
+
+/// An async connection pool over a single `libsql::Database` handle.
+pub struct DbPool {
+    database: Database,
+    semaphore: Arc<Semaphore>,
+    acquire_timeout: Duration,
+}
+
+impl DbPool {
+    /// Wrap a `Database` handle in a pool with the given configuration.
+    pub fn new(database: Database, config: PoolConfig) -> Self {
+        Self {
+            database,
+            semaphore: Arc::new(Semaphore::new(config.max_size)),
+            acquire_timeout: config.acquire_timeout,
+        }
+    }
+
+    /// Checks out a connection, waiting up to `acquire_timeout` for a free
+    /// slot. The connection is health-checked (and transparently
+    /// reconnected once if the check fails) before it is handed back.
+    pub async fn get(&self) -> Result<PooledConnection> {
+        let permit = tokio::time::timeout(self.acquire_timeout, self.semaphore.clone().acquire_owned())
+            .await
+            .map_err(|_| Error::msg("timed out waiting for a free database connection"))?
+            .map_err(|_| Error::msg("database connection pool has been closed"))?;
+
+        let connection = self.checkout_healthy().await?;
+        Ok(PooledConnection {
+            connection,
+            _permit: permit,
+        })
+    }
+
+    /// Connects and verifies the handle with a cheap `SELECT 1`, reconnecting
+    /// once if the check fails before giving up.
+    async fn checkout_healthy(&self) -> Result<Connection> {
+        let connection = self.database.connect()?;
+        if connection.query("SELECT 1", ()).await.is_ok() {
+            return Ok(connection);
+        }
+
+        let connection = self.database.connect()?;
+        connection.query("SELECT 1", ()).await?;
+        Ok(connection)
+    }
+}
+
+/// A checked-out connection. Holds the semaphore permit that bounds the
+/// pool's size for as long as it is alive; dropping it returns the slot.
+pub struct PooledConnection {
+    connection: Connection,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl std::ops::Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn get_times_out_once_every_permit_is_held() {
+        let database = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let pool = DbPool::new(
+            database,
+            PoolConfig {
+                max_size: 1,
+                acquire_timeout: Duration::from_millis(50),
+            },
+        );
+
+        let held = pool.get().await.unwrap();
+
+        assert!(pool.get().await.is_err());
+
+        drop(held);
+        assert!(pool.get().await.is_ok());
+    }
+}
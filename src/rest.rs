@@ -0,0 +1,63 @@
+//! HTTP routes for the posts resource.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::get,
+    Router,
+};
+
+use crate::auth::{Guard, PostsWrite};
+use crate::db::{self, Post};
+use crate::error::AppError;
+use crate::pool::DbPool;
+
+/// Build the router for the `/posts` resource.
+pub fn posts_service() -> Router {
+    Router::new()
+        .route("/", get(list_posts).post(create_post))
+        .route("/{id}", get(get_post).put(update_post).delete(delete_post))
+}
+
+async fn list_posts(Extension(pool): Extension<Arc<DbPool>>) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(db::all_books(&pool).await?))
+}
+
+async fn get_post(
+    Extension(pool): Extension<Arc<DbPool>>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(db::book_by_id(&pool, id).await?))
+}
+
+async fn create_post(
+    Extension(pool): Extension<Arc<DbPool>>,
+    _guard: Guard<PostsWrite>,
+    Json(post): Json<Post>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = db::add_post(&pool, &post).await?;
+    Ok((StatusCode::CREATED, Json(id)))
+}
+
+async fn update_post(
+    Extension(pool): Extension<Arc<DbPool>>,
+    Path(id): Path<i32>,
+    _guard: Guard<PostsWrite>,
+    Json(mut post): Json<Post>,
+) -> Result<impl IntoResponse, AppError> {
+    post.id = Some(id);
+    db::update_post(&pool, &post).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_post(
+    Extension(pool): Extension<Arc<DbPool>>,
+    Path(id): Path<i32>,
+    _guard: Guard<PostsWrite>,
+) -> Result<impl IntoResponse, AppError> {
+    db::delete_post(&pool, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}